@@ -0,0 +1,246 @@
+use crate::JsonRpcError;
+use anyhow::{Context, Result};
+use chrono::{SecondsFormat, Utc};
+use serde_json::{json, Map, Value};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// Number of rotated files (`<path>.1` ... `<path>.N`) kept besides the live log.
+const ROTATED_FILES_KEPT: usize = 5;
+
+/// Arguments whose values are message content and never written to the log; only their length is.
+const REDACTED_ARGUMENTS: &[&str] = &["body", "body_html", "template", "template_html"];
+
+/// A successful tool call: the MCP result plus what the audit log records about it.
+pub struct ToolOutcome {
+    pub result: Value,
+    /// `Message-ID` of the email sent by the call, if any.
+    pub message_id: Option<String>,
+    /// Envelope recipients of the email sent by the call, if any.
+    pub recipients: Vec<String>,
+}
+
+impl ToolOutcome {
+    /// An outcome that sent nothing.
+    pub fn new(result: Value) -> Self {
+        Self {
+            result,
+            message_id: None,
+            recipients: Vec::new(),
+        }
+    }
+}
+
+/// A handle for appending to the audit log: an append-only JSON-lines log of
+/// tool calls, rotated by size.
+///
+/// Entries are queued to one writer on a blocking thread, so file writes and
+/// rotation never stall the async runtime and entries never interleave.
+#[derive(Clone)]
+pub struct AuditLog {
+    sender: mpsc::UnboundedSender<String>,
+}
+
+impl AuditLog {
+    /// Starts the writer for the log at `path`.
+    ///
+    /// The writer ends once every `AuditLog` has been dropped and the queue is
+    /// drained; awaiting the returned handle then guarantees every entry has been written.
+    pub fn spawn(path: PathBuf, max_bytes: u64) -> (Self, JoinHandle<()>) {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<String>();
+        let file = LogFile { path, max_bytes };
+        let writer = tokio::task::spawn_blocking(move || {
+            while let Some(line) = receiver.blocking_recv() {
+                if let Err(e) = file.append(&line) {
+                    eprintln!("Warning: failed to write audit log {}: {:#}", file.path.display(), e);
+                }
+            }
+        });
+        (Self { sender }, writer)
+    }
+
+    /// Queues one entry for a call to `tool`, which is `None` when the request didn't name one.
+    ///
+    /// Message content in `args` is redacted. A failure to write is reported
+    /// on stderr rather than failing the call, which may already have sent mail.
+    pub fn record(&self, tool: Option<&str>, args: &Value, outcome: &Result<ToolOutcome, JsonRpcError>) {
+        let mut entry = json!({
+            "timestamp": Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            "account": std::env::var("GMAIL_USER").ok(),
+            "tool": tool,
+            "arguments": sanitize(args),
+        });
+        match outcome {
+            Ok(outcome) => {
+                entry["status"] = json!("ok");
+                if let Some(message_id) = &outcome.message_id {
+                    entry["message_id"] = json!(message_id);
+                    entry["recipients"] = json!(outcome.recipients);
+                }
+            }
+            Err(e) => {
+                entry["status"] = json!("error");
+                entry["error"] = json!({ "code": e.code, "message": e.message });
+            }
+        }
+
+        // The writer only stops once every handle is gone.
+        let _ = self.sender.send(entry.to_string());
+    }
+}
+
+/// The log file itself, owned by the writer thread.
+struct LogFile {
+    path: PathBuf,
+    max_bytes: u64,
+}
+
+impl LogFile {
+    fn append(&self, line: &str) -> Result<()> {
+        let size = fs::metadata(&self.path).map(|m| m.len()).unwrap_or(0);
+        if size > 0 && size + line.len() as u64 + 1 > self.max_bytes {
+            self.rotate().context("Failed to rotate")?;
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .context("Failed to open")?;
+        writeln!(file, "{}", line).context("Failed to append")?;
+        Ok(())
+    }
+
+    /// Shifts `<path>.N-1` to `<path>.N` (dropping the oldest) and moves the live log to `<path>.1`.
+    fn rotate(&self) -> std::io::Result<()> {
+        for n in (1..ROTATED_FILES_KEPT).rev() {
+            let from = rotated_path(&self.path, n);
+            if from.exists() {
+                fs::rename(&from, rotated_path(&self.path, n + 1))?;
+            }
+        }
+        fs::rename(&self.path, rotated_path(&self.path, 1))
+    }
+}
+
+fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(format!(".{}", n));
+    PathBuf::from(rotated)
+}
+
+/// A copy of tool arguments that is safe to log.
+///
+/// Bodies and templates are replaced by their length, template variable values
+/// are dropped (keeping their names), and query strings are cut from URLs
+/// since they often carry access tokens.
+fn sanitize(args: &Value) -> Value {
+    let Some(args) = args.as_object() else {
+        return Value::Null;
+    };
+
+    let mut sanitized = Map::new();
+    for (key, value) in args {
+        let value = match (key.as_str(), value) {
+            (key, Value::String(s)) if REDACTED_ARGUMENTS.contains(&key) => {
+                json!(format!("[redacted, {} chars]", s.chars().count()))
+            }
+            // A malformed value is still message content; don't log it either.
+            (key, _) if REDACTED_ARGUMENTS.contains(&key) => json!("[redacted]"),
+            ("variables", Value::Object(variables)) => {
+                json!(variables.keys().map(|name| (name.clone(), json!("[redacted]"))).collect::<Map<_, _>>())
+            }
+            ("attachments_url", Value::Array(items)) => Value::Array(
+                items
+                    .iter()
+                    .map(|item| {
+                        let mut item = item.clone();
+                        if let Some(url) = item.get("url").and_then(|u| u.as_str()) {
+                            item["url"] = json!(strip_query(url));
+                        }
+                        item
+                    })
+                    .collect(),
+            ),
+            _ => value.clone(),
+        };
+        sanitized.insert(key.clone(), value);
+    }
+    Value::Object(sanitized)
+}
+
+/// Removes the query string and fragment from `url`.
+fn strip_query(url: &str) -> &str {
+    url.split(['?', '#']).next().unwrap_or(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn message_content_is_redacted() {
+        let args = json!({
+            "to": "a@example.com",
+            "body": "héllo",
+            "body_html": "<p>hi</p>",
+            "template": "Hi {{name}}",
+            "template_html": { "unexpected": "object" },
+            "variables": { "name": "Ada", "code": 1234 },
+        });
+        assert_eq!(
+            sanitize(&args),
+            json!({
+                "to": "a@example.com",
+                "body": "[redacted, 5 chars]",
+                "body_html": "[redacted, 9 chars]",
+                "template": "[redacted, 11 chars]",
+                "template_html": "[redacted]",
+                "variables": { "name": "[redacted]", "code": "[redacted]" },
+            })
+        );
+        assert_eq!(sanitize(&json!({ "body": ["a", "b"] })), json!({ "body": "[redacted]" }));
+        assert_eq!(sanitize(&Value::Null), Value::Null);
+    }
+
+    #[test]
+    fn attachment_url_queries_are_stripped() {
+        let args = json!({
+            "attachments_url": [
+                { "url": "https://example.com/a.pdf?token=secret#page=2", "filename": "a.pdf" },
+                { "url": "https://example.com/b.pdf" },
+            ],
+        });
+        assert_eq!(
+            sanitize(&args)["attachments_url"],
+            json!([
+                { "url": "https://example.com/a.pdf", "filename": "a.pdf" },
+                { "url": "https://example.com/b.pdf" },
+            ])
+        );
+    }
+
+    #[test]
+    fn rotation_shifts_files_and_keeps_five() {
+        let dir = std::env::temp_dir().join(format!("gmail-mcp-audit-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("audit.log");
+        // Each line fills the log, so every append after the first rotates.
+        let file = LogFile { path: path.clone(), max_bytes: 3 };
+
+        for n in 0..8 {
+            file.append(&n.to_string()).unwrap();
+        }
+        let read = |path: &Path| fs::read_to_string(path).unwrap();
+        assert_eq!(read(&path), "7\n");
+        assert_eq!(read(&rotated_path(&path, 1)), "6\n");
+        assert_eq!(read(&rotated_path(&path, 2)), "5\n");
+        assert_eq!(read(&rotated_path(&path, 5)), "2\n");
+        assert!(!rotated_path(&path, 6).exists());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
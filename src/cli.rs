@@ -0,0 +1,130 @@
+use crate::gmail_client::{ClientOptions, TlsVersion};
+use clap::builder::PossibleValuesParser;
+use clap::{Parser, Subcommand};
+use lettre::message::header::ContentType;
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+/// Command-line options for the Gmail MCP server.
+///
+/// With no subcommand the server speaks JSON-RPC over stdin/stdout.
+#[derive(Parser, Debug, Clone)]
+#[command(name = "gmail-mcp-server", version)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Maximum size in bytes of a single JSON-RPC request line. Larger
+    /// requests are discarded and answered with an "Invalid Request" error.
+    #[arg(long, value_name = "BYTES", default_value_t = 10 * 1024 * 1024)]
+    pub max_body_bytes: usize,
+
+    /// Mark every outgoing email as test traffic with an `X-Sandbox` header.
+    #[arg(long)]
+    pub sandbox: bool,
+
+    /// Value of the `X-Sandbox` header in `--sandbox` mode.
+    #[arg(long, value_name = "LABEL", default_value = "mcp-sandbox", requires = "sandbox")]
+    pub sandbox_label: String,
+
+    /// Directory of named templates for `send_templated` (`<name>.txt` and/or `<name>.html`).
+    #[arg(long, value_name = "DIR")]
+    pub template_dir: Option<PathBuf>,
+
+    /// Override the MIME type for an attachment file extension, e.g. `heic=image/heic`.
+    /// May be given multiple times.
+    #[arg(long = "mime-type", value_name = "EXT=TYPE", value_parser = parse_mime_override)]
+    pub mime_overrides: Vec<(String, String)>,
+
+    /// Connect and authenticate to Gmail SMTP at startup so the first send
+    /// doesn't pay for the handshake. Skipped with a warning when credentials
+    /// are missing.
+    #[arg(long)]
+    pub prewarm: bool,
+
+    /// Handle this single JSON-RPC request, print the response and exit,
+    /// instead of serving stdin. No `initialize` handshake is needed.
+    #[arg(long, value_name = "REQUEST")]
+    pub once: Option<String>,
+
+    /// Append a JSON line per tool call (time, tool, redacted arguments,
+    /// outcome, Message-ID) to this file.
+    #[arg(long, value_name = "PATH")]
+    pub audit_log: Option<PathBuf>,
+
+    /// Rotate the audit log to `<PATH>.1` once it would grow past this size.
+    #[arg(long, value_name = "BYTES", default_value_t = 10 * 1024 * 1024, requires = "audit_log")]
+    pub audit_log_max_bytes: u64,
+
+    /// Oldest TLS version accepted for the Gmail SMTP connection and for
+    /// attachment downloads.
+    #[arg(long, value_name = "VERSION", value_enum, default_value_t = TlsVersion::Tls12)]
+    pub min_tls_version: TlsVersion,
+
+    /// Serve HTTP `/healthz` (liveness) and `/readyz` (credentials log in to
+    /// Gmail SMTP) probes on this port.
+    #[arg(long, value_name = "PORT")]
+    pub health_port: Option<u16>,
+
+    /// Address the `--health-port` probes listen on. Use `0.0.0.0` to reach
+    /// them from other hosts, e.g. a container orchestrator.
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1", requires = "health_port")]
+    pub health_bind: IpAddr,
+
+    /// Refuse every request that attaches files and leave the attachment
+    /// arguments out of the advertised tool schemas.
+    #[arg(long)]
+    pub deny_attachments: bool,
+
+    /// Expose only these tools (comma-separated). All tools are enabled by default.
+    #[arg(long, value_name = "TOOLS", value_delimiter = ',', value_parser = PossibleValuesParser::new(crate::TOOL_NAMES))]
+    pub enabled_tools: Vec<String>,
+
+    /// Hide and refuse these tools (comma-separated), even if listed in `--enabled-tools`.
+    #[arg(long, value_name = "TOOLS", value_delimiter = ',', value_parser = PossibleValuesParser::new(crate::TOOL_NAMES))]
+    pub disabled_tools: Vec<String>,
+
+    /// Enable the `echo` method, which returns its `params` unchanged, for
+    /// debugging client framing and encoding. Not for production use.
+    #[arg(long)]
+    pub debug: bool,
+}
+
+impl Cli {
+    /// Message-building settings for [`GmailClient`](crate::gmail_client::GmailClient).
+    pub fn client_options(&self) -> ClientOptions {
+        ClientOptions {
+            mime_overrides: self.mime_overrides.iter().cloned().collect(),
+            sandbox_label: self.sandbox.then(|| self.sandbox_label.clone()),
+            min_tls_version: self.min_tls_version,
+        }
+    }
+
+    /// Whether tool `name` is exposed under `--enabled-tools` / `--disabled-tools`.
+    pub fn tool_enabled(&self, name: &str) -> bool {
+        (self.enabled_tools.is_empty() || self.enabled_tools.iter().any(|t| t == name))
+            && !self.disabled_tools.iter().any(|t| t == name)
+    }
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Print the `tools/list` result as JSON and exit.
+    Capabilities,
+}
+
+/// Parses an `EXT=TYPE` MIME override, normalizing the extension.
+fn parse_mime_override(s: &str) -> Result<(String, String), String> {
+    let (extension, mime_type) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected EXT=TYPE, got '{}'", s))?;
+    let extension = extension.trim().trim_start_matches('.').to_ascii_lowercase();
+    let mime_type = mime_type.trim();
+
+    if extension.is_empty() {
+        return Err(format!("missing extension in '{}'", s));
+    }
+    ContentType::parse(mime_type).map_err(|_| format!("invalid MIME type '{}'", mime_type))?;
+
+    Ok((extension, mime_type.to_string()))
+}
@@ -0,0 +1,291 @@
+use anyhow::{bail, Context, Result};
+use bytes::Bytes;
+use http_body_util::{BodyExt, Empty};
+use crate::gmail_client::TlsVersion;
+use hyper_rustls::{ConfigBuilderExt, HttpsConnectorBuilder};
+use hyper_util::client::legacy::connect::dns::{GaiResolver, Name};
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+use rustls::ClientConfig;
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context as TaskContext, Poll};
+use std::time::Duration;
+use tower_service::Service;
+use url::{Host, Url};
+
+/// How long a single download (including redirects) may take.
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Maximum number of redirects followed for one download.
+const MAX_REDIRECTS: usize = 5;
+
+/// A file fetched over HTTPS.
+pub struct Download {
+    pub content: Vec<u8>,
+    /// The response's `Content-Type` header, if any.
+    pub content_type: Option<String>,
+    /// The final URL after redirects.
+    pub url: Url,
+}
+
+/// Downloads `url` over HTTPS, failing if the body exceeds `max_bytes`.
+///
+/// Only `https://` URLs are accepted, including redirect targets, and the
+/// connection must negotiate at least `min_tls_version`. Hosts that are, or
+/// resolve to, loopback, private, link-local or unspecified addresses are
+/// refused on every hop so internal services can't be mailed out.
+pub async fn fetch(url: &str, max_bytes: u64, min_tls_version: TlsVersion) -> Result<Download> {
+    let url = parse_https(url)?;
+
+    tokio::time::timeout(DOWNLOAD_TIMEOUT, fetch_following_redirects(url, max_bytes, min_tls_version))
+        .await
+        .map_err(|_| anyhow::anyhow!("Download timed out after {} seconds", DOWNLOAD_TIMEOUT.as_secs()))?
+}
+
+async fn fetch_following_redirects(mut url: Url, max_bytes: u64, min_tls_version: TlsVersion) -> Result<Download> {
+    let provider = Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+    let tls_config = ClientConfig::builder_with_provider(provider)
+        .with_protocol_versions(min_tls_version.rustls_versions())
+        .context("Unsupported TLS configuration")?
+        .with_native_roots()
+        .context("Failed to load native root certificates")?
+        .with_no_client_auth();
+    // The TLS layer needs the connector to accept https:// URIs.
+    let mut http = HttpConnector::new_with_resolver(PublicResolver(GaiResolver::new()));
+    http.enforce_http(false);
+    let https = HttpsConnectorBuilder::new()
+        .with_tls_config(tls_config)
+        .https_only()
+        .enable_http1()
+        .enable_http2()
+        .wrap_connector(http);
+    let client: Client<_, Empty<Bytes>> = Client::builder(TokioExecutor::new()).build(https);
+
+    for _ in 0..=MAX_REDIRECTS {
+        let request = http::Request::get(url.as_str())
+            .header(http::header::USER_AGENT, concat!("gmail-mcp-server/", env!("CARGO_PKG_VERSION")))
+            .body(Empty::new())
+            .context("Failed to build download request")?;
+        // hyper's own message is generic ("client error (Connect)"); the reason is in its sources.
+        let response = client
+            .request(request)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to download {}: {}", url, error_chain(&e)))?;
+
+        let status = response.status();
+        if status.is_redirection() {
+            let location = response
+                .headers()
+                .get(http::header::LOCATION)
+                .and_then(|l| l.to_str().ok())
+                .with_context(|| format!("Redirect from {} has no Location header", url))?;
+            url = parse_https(url.join(location).context("Invalid redirect location")?.as_str())?;
+            continue;
+        }
+        if !status.is_success() {
+            bail!("Download of {} failed with HTTP status {}", url, status);
+        }
+
+        let too_large = || anyhow::anyhow!("Download of {} exceeds the {} byte attachment limit", url, max_bytes);
+        let declared_len = response
+            .headers()
+            .get(http::header::CONTENT_LENGTH)
+            .and_then(|l| l.to_str().ok())
+            .and_then(|l| l.parse::<u64>().ok());
+        if declared_len.is_some_and(|len| len > max_bytes) {
+            return Err(too_large());
+        }
+
+        let content_type = response
+            .headers()
+            .get(http::header::CONTENT_TYPE)
+            .and_then(|t| t.to_str().ok())
+            .map(str::to_string);
+
+        // Stream the body so an unannounced oversized response is cut off early.
+        let mut body = response.into_body();
+        let mut content = Vec::new();
+        while let Some(frame) = body.frame().await {
+            let frame = frame.with_context(|| format!("Failed to read response from {}", url))?;
+            if let Some(chunk) = frame.data_ref() {
+                if (content.len() + chunk.len()) as u64 > max_bytes {
+                    return Err(too_large());
+                }
+                content.extend_from_slice(chunk);
+            }
+        }
+
+        return Ok(Download { content, content_type, url });
+    }
+
+    bail!("Too many redirects while downloading {}", url)
+}
+
+/// `error` followed by each of its sources, separated by `: `.
+fn error_chain(error: &dyn std::error::Error) -> String {
+    let mut chain = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        chain.push_str(": ");
+        chain.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    chain
+}
+
+/// Parses `url`, refusing anything but `https://` URLs and literal non-public addresses.
+pub fn parse_https(url: &str) -> Result<Url> {
+    let url = Url::parse(url).with_context(|| format!("Invalid URL '{}'", url))?;
+    if url.scheme() != "https" {
+        bail!("Only https:// URLs can be attached, got '{}'", url);
+    }
+    // Addresses written into the URL never reach the resolver, so check them here.
+    let literal = match url.host() {
+        Some(Host::Ipv4(ip)) => Some(IpAddr::V4(ip)),
+        Some(Host::Ipv6(ip)) => Some(IpAddr::V6(ip)),
+        _ => None,
+    };
+    if let Some(ip) = literal.filter(|ip| !is_public(*ip)) {
+        bail!("Refusing to download from non-public address {} in '{}'", ip, url);
+    }
+    Ok(url)
+}
+
+/// Whether `ip` is routable on the public internet rather than loopback,
+/// private, link-local (including cloud metadata endpoints), unspecified or
+/// otherwise special-purpose. IPv6 addresses that embed an IPv4 address are
+/// judged by the address they embed.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            let special = match a {
+                // "This network", 0.0.0.0/8.
+                0 => true,
+                // Carrier-grade NAT space, 100.64.0.0/10, internal in practice.
+                100 => (64..128).contains(&b),
+                // IETF protocol assignments, 192.0.0.0/24.
+                192 => b == 0 && c == 0,
+                // Benchmarking, 198.18.0.0/15.
+                198 => b & 0xfe == 18,
+                // Reserved, 240.0.0.0/4, including broadcast.
+                240.. => true,
+                _ => false,
+            };
+            !(ip.is_loopback() || ip.is_private() || ip.is_link_local() || special)
+        }
+        IpAddr::V6(ip) => {
+            // Forms that carry an IPv4 address, which a translator may route to it.
+            let segments = ip.segments();
+            let [_, _, _, _, _, _, high, low] = segments;
+            let last_32 = Ipv4Addr::from((u32::from(high) << 16) | u32::from(low));
+            let embedded = if let Some(mapped) = ip.to_ipv4_mapped() {
+                Some(mapped)
+            } else if segments[..6] == [0; 6] && !ip.is_loopback() && !ip.is_unspecified() {
+                // IPv4-compatible, ::a.b.c.d.
+                Some(last_32)
+            } else if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+                // NAT64, 64:ff9b::/96.
+                Some(last_32)
+            } else if segments[0] == 0x2002 {
+                // 6to4, 2002:aabb:ccdd::/48.
+                Some(Ipv4Addr::from((u32::from(segments[1]) << 16) | u32::from(segments[2])))
+            } else {
+                None
+            };
+            match embedded {
+                Some(v4) => is_public(IpAddr::V4(v4)),
+                None => !(ip.is_loopback() || ip.is_unspecified() || ip.is_unique_local() || ip.is_unicast_link_local()),
+            }
+        }
+    }
+}
+
+/// A DNS resolver that fails when a name resolves to any non-public address.
+///
+/// Checking at connect time, rather than resolving separately beforehand,
+/// means a name can't pass the check and then be re-pointed at an internal host.
+#[derive(Clone)]
+struct PublicResolver(GaiResolver);
+
+impl Service<Name> for PublicResolver {
+    type Response = std::vec::IntoIter<SocketAddr>;
+    type Error = std::io::Error;
+    type Future = Pin<Box<dyn Future<Output = std::io::Result<Self::Response>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<std::io::Result<()>> {
+        self.0.poll_ready(cx)
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        let host = name.as_str().to_string();
+        let lookup = self.0.call(name);
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = lookup.await?.collect();
+            if let Some(addr) = addrs.iter().find(|addr| !is_public(addr.ip())) {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::PermissionDenied,
+                    format!("refusing to download from {}, which resolves to non-public address {}", host, addr.ip()),
+                ));
+            }
+            Ok(addrs.into_iter())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn internal_addresses_are_not_public() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "0.1.2.3",
+            "192.0.0.8",
+            "198.18.0.1",
+            "198.19.255.255",
+            "240.0.0.1",
+            "255.255.255.255",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::127.0.0.1",
+            "64:ff9b::a00:1",
+            "2002:7f00:1::1",
+            "2002:a9fe:a9fe::1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{} should be refused", ip);
+        }
+        for ip in [
+            "8.8.8.8",
+            "142.250.72.14",
+            "198.20.0.1",
+            "2607:f8b0:4005:80c::200e",
+            "64:ff9b::808:808",
+            "2002:808:808::1",
+        ] {
+            assert!(is_public(ip.parse().unwrap()), "{} should be allowed", ip);
+        }
+    }
+
+    #[test]
+    fn literal_internal_hosts_are_refused() {
+        assert!(parse_https("https://169.254.169.254/latest/meta-data/").is_err());
+        assert!(parse_https("https://[::1]:8443/").is_err());
+        assert!(parse_https("http://example.com/a.pdf").is_err());
+        assert!(parse_https("https://example.com/a.pdf").is_ok());
+    }
+}
@@ -0,0 +1,124 @@
+use crate::cli::Cli;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+
+/// How long a readiness result is reused before logging in to SMTP again.
+const READINESS_TTL: Duration = Duration::from_secs(60);
+
+/// How long a readiness login may take before it counts as a failure.
+const READINESS_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a probe may take to send its request line.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Pause after a failed accept, so running out of file descriptors doesn't spin.
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Largest probe request read; anything past the request line is ignored.
+const MAX_REQUEST_BYTES: usize = 8 * 1024;
+
+/// The last readiness check: when it ran and why it failed, if it did.
+type Readiness = Arc<Mutex<Option<(Instant, Result<(), String>)>>>;
+
+/// Serves `/healthz` and `/readyz` probes on `listener` until the process exits.
+///
+/// `/healthz` answers `200` whenever the process is running. `/readyz` answers
+/// `200` when the Gmail credentials log in to SMTP and `503` otherwise, with
+/// the reason logged to stderr rather than returned; the result is cached for
+/// [`READINESS_TTL`] so frequent probes don't hammer Gmail.
+pub async fn serve(listener: TcpListener, cli: Cli) {
+    let cli = Arc::new(cli);
+    let readiness: Readiness = Arc::default();
+
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                eprintln!("Warning: health check accept failed: {}", e);
+                tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+                continue;
+            }
+        };
+        let cli = Arc::clone(&cli);
+        let readiness = Arc::clone(&readiness);
+        tokio::spawn(async move {
+            if let Err(e) = respond(stream, &cli, &readiness).await {
+                eprintln!("Warning: health check request failed: {}", e);
+            }
+        });
+    }
+}
+
+async fn respond(mut stream: TcpStream, cli: &Cli, readiness: &Readiness) -> std::io::Result<()> {
+    let request_line = match tokio::time::timeout(REQUEST_TIMEOUT, read_request_line(&mut stream)).await {
+        Ok(line) => line?,
+        Err(_) => return Ok(()),
+    };
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next();
+    let (status, body) = match (method, parts.next()) {
+        (Some("GET" | "HEAD"), Some("/healthz")) => ("200 OK", "ok".to_string()),
+        (Some("GET" | "HEAD"), Some("/readyz")) => match check_ready(cli, readiness).await {
+            Ok(()) => ("200 OK", "ready".to_string()),
+            // The reason can carry SMTP server text, so it only goes to stderr.
+            Err(_) => ("503 Service Unavailable", "not ready".to_string()),
+        },
+        (Some("GET" | "HEAD"), Some(_)) => ("404 Not Found", "not found".to_string()),
+        _ => ("405 Method Not Allowed", "method not allowed".to_string()),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        if method == Some("HEAD") { "" } else { &body }
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Reads up to the end of the HTTP request line.
+async fn read_request_line(stream: &mut TcpStream) -> std::io::Result<String> {
+    let mut buf = Vec::new();
+    let mut chunk = [0; 1024];
+    while !buf.contains(&b'\n') && buf.len() < MAX_REQUEST_BYTES {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+    let line = buf.split(|&b| b == b'\n').next().unwrap_or_default();
+    Ok(String::from_utf8_lossy(line).trim().to_string())
+}
+
+/// Returns the cached readiness result, logging in to SMTP again once it is stale.
+///
+/// The lock is held during the check so concurrent probes share one login;
+/// the login is bounded by [`READINESS_CHECK_TIMEOUT`] so a hung connection
+/// can't leave probes queued behind it indefinitely.
+async fn check_ready(cli: &Cli, readiness: &Readiness) -> Result<(), String> {
+    let mut cached = readiness.lock().await;
+    if let Some((checked_at, result)) = cached.as_ref() {
+        if checked_at.elapsed() < READINESS_TTL {
+            return result.clone();
+        }
+    }
+
+    let result = match crate::client_from_env(cli) {
+        Ok(client) => match tokio::time::timeout(READINESS_CHECK_TIMEOUT, client.test_connection()).await {
+            Ok(result) => result.map_err(|e| e.to_string()),
+            Err(_) => Err(format!("SMTP login timed out after {} seconds", READINESS_CHECK_TIMEOUT.as_secs())),
+        },
+        Err(e) => Err(e.message),
+    };
+    if let Err(reason) = &result {
+        eprintln!("Warning: readiness check failed: {}", reason);
+    }
+    *cached = Some((Instant::now(), result.clone()));
+    result
+}
@@ -0,0 +1,325 @@
+/// Converts an HTML document into a readable plain-text rendition.
+///
+/// This is not a full HTML parser; it handles the markup that commonly appears
+/// in email bodies: paragraphs and headings become blank-line separated blocks,
+/// `<br>` becomes a line break, list items are prefixed with `-` (or their
+/// number inside `<ol>`), and links are rendered as `text (href)`. The contents
+/// of `<script>`, `<style>` and `<head>` are dropped and character entities
+/// are decoded.
+pub fn html_to_text(html: &str) -> String {
+    let mut converter = Converter::default();
+    let mut rest = html;
+
+    while let Some(lt) = rest.find('<') {
+        converter.text(&rest[..lt]);
+        rest = &rest[lt..];
+
+        // A `<` that can't start a tag, as in `a < b`, is just text.
+        if !rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || matches!(c, '/' | '!' | '?')) {
+            converter.text("<");
+            rest = &rest[1..];
+            continue;
+        }
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+
+        match rest.find('>') {
+            Some(gt) => {
+                converter.tag(&rest[1..gt]);
+                rest = &rest[gt + 1..];
+            }
+            None => {
+                // Unterminated tag: treat the remainder as text.
+                converter.text(rest);
+                rest = "";
+            }
+        }
+    }
+    converter.text(rest);
+
+    converter.finish()
+}
+
+#[derive(Default)]
+struct Converter {
+    out: String,
+    /// Nesting depth inside elements whose content is not rendered.
+    skip_depth: usize,
+    /// Nesting depth inside `<pre>`, where whitespace is preserved.
+    pre_depth: usize,
+    /// One entry per open list: `None` for `<ul>`, the next number for `<ol>`.
+    lists: Vec<Option<usize>>,
+    /// The `href` of the open link and where its text starts in `out`.
+    link: Option<(String, usize)>,
+}
+
+impl Converter {
+    fn text(&mut self, raw: &str) {
+        if self.skip_depth > 0 || raw.is_empty() {
+            return;
+        }
+        let decoded = decode_entities(raw);
+
+        if self.pre_depth > 0 {
+            self.out.push_str(&decoded);
+            return;
+        }
+
+        for (i, word) in decoded.split_whitespace().enumerate() {
+            let starts_with_space = i > 0 || decoded.starts_with(char::is_whitespace);
+            if starts_with_space && !self.out.is_empty() && !self.out.ends_with(['\n', ' ']) {
+                self.out.push(' ');
+            }
+            self.out.push_str(word);
+        }
+        if decoded.ends_with(char::is_whitespace) && !self.out.is_empty() && !self.out.ends_with(['\n', ' ']) {
+            self.out.push(' ');
+        }
+    }
+
+    fn tag(&mut self, tag: &str) {
+        let closing = tag.starts_with('/');
+        let tag = tag.trim_start_matches('/');
+        let name_end = tag.find(|c: char| c.is_whitespace() || c == '/').unwrap_or(tag.len());
+        let name = tag[..name_end].to_ascii_lowercase();
+
+        if matches!(name.as_str(), "script" | "style" | "head" | "title") {
+            if closing {
+                self.skip_depth = self.skip_depth.saturating_sub(1);
+            } else if !tag.ends_with('/') {
+                self.skip_depth += 1;
+            }
+            return;
+        }
+        if self.skip_depth > 0 {
+            return;
+        }
+
+        match (name.as_str(), closing) {
+            ("br", _) => self.newline(),
+            ("hr", false) => {
+                self.block(2);
+                self.out.push_str("----");
+                self.block(2);
+            }
+            ("p" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "blockquote" | "table", _) => self.block(2),
+            ("div" | "tr" | "section" | "article" | "header" | "footer", _) => self.block(1),
+            ("pre", false) => {
+                self.block(2);
+                self.pre_depth += 1;
+            }
+            ("pre", true) => {
+                self.pre_depth = self.pre_depth.saturating_sub(1);
+                self.block(2);
+            }
+            ("ul", false) => {
+                self.block(if self.lists.is_empty() { 2 } else { 1 });
+                self.lists.push(None);
+            }
+            ("ol", false) => {
+                self.block(if self.lists.is_empty() { 2 } else { 1 });
+                self.lists.push(Some(1));
+            }
+            ("ul" | "ol", true) => {
+                self.lists.pop();
+                self.block(if self.lists.is_empty() { 2 } else { 1 });
+            }
+            ("li", false) => {
+                self.block(1);
+                let indent = "  ".repeat(self.lists.len().saturating_sub(1));
+                self.out.push_str(&indent);
+                match self.lists.last_mut() {
+                    Some(Some(n)) => {
+                        self.out.push_str(&format!("{}. ", n));
+                        *n += 1;
+                    }
+                    _ => self.out.push_str("- "),
+                }
+            }
+            ("td" | "th", false) if !self.out.is_empty() && !self.out.ends_with(['\n', ' ']) => {
+                self.out.push(' ');
+            }
+            ("a", false) => {
+                self.link = attribute(tag, "href").map(|href| (href, self.out.len()));
+            }
+            ("a", true) => {
+                if let Some((href, start)) = self.link.take() {
+                    let text = self.out[start.min(self.out.len())..].trim();
+                    let target = href.strip_prefix("mailto:").unwrap_or(&href);
+                    if !href.is_empty() && !href.starts_with('#') && text != target {
+                        if text.is_empty() {
+                            self.out.push_str(&href);
+                        } else {
+                            self.out.push_str(&format!(" ({})", href));
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn newline(&mut self) {
+        while self.out.ends_with(' ') {
+            self.out.pop();
+        }
+        self.out.push('\n');
+    }
+
+    /// Ends the current line and makes sure `lines` line breaks separate it from what follows.
+    fn block(&mut self, lines: usize) {
+        while self.out.ends_with(' ') {
+            self.out.pop();
+        }
+        if self.out.is_empty() {
+            return;
+        }
+        let existing = self.out.len() - self.out.trim_end_matches('\n').len();
+        for _ in existing..lines {
+            self.out.push('\n');
+        }
+    }
+
+    fn finish(self) -> String {
+        let mut text = String::new();
+        let mut blank_lines = 0;
+        for line in self.out.lines().map(str::trim_end) {
+            if line.is_empty() {
+                blank_lines += 1;
+                if blank_lines > 1 {
+                    continue;
+                }
+            } else {
+                blank_lines = 0;
+            }
+            text.push_str(line);
+            text.push('\n');
+        }
+        // Leading spaces can only come from `<pre>` content, so keep them.
+        text.trim_start_matches('\n').trim_end().to_string()
+    }
+}
+
+/// Extracts the value of attribute `name` from the inside of a start tag.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let lower = tag.to_ascii_lowercase();
+    let mut search_from = 0;
+    while let Some(found) = lower[search_from..].find(name) {
+        let start = search_from + found;
+        search_from = start + name.len();
+
+        let preceded_by_space = lower[..start].ends_with(char::is_whitespace);
+        let after = lower[search_from..].trim_start();
+        if !preceded_by_space || !after.starts_with('=') {
+            continue;
+        }
+
+        let value_start = tag.len() - after.len() + 1;
+        let value = tag[value_start..].trim_start();
+        let value = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => value[1..].split(quote).next().unwrap_or(""),
+            _ => value.split(|c: char| c.is_whitespace()).next().unwrap_or(""),
+        };
+        return Some(decode_entities(value));
+    }
+    None
+}
+
+/// Decodes the common named character entities and all numeric ones.
+fn decode_entities(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+
+        let decoded = rest.find(';').filter(|&end| end <= 10).and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                _ => entity.strip_prefix('#').and_then(|num| {
+                    let code = match num.strip_prefix(['x', 'X']) {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                        None => num.parse().ok(),
+                    };
+                    code.and_then(char::from_u32)
+                }),
+            };
+            c.map(|c| (c, end))
+        });
+
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paragraphs_and_line_breaks() {
+        assert_eq!(html_to_text("<p>First  paragraph</p><p>Second<br>line</p>"), "First paragraph\n\nSecond\nline");
+    }
+
+    #[test]
+    fn nested_lists() {
+        let html = "<ul><li>One<ol><li>A</li><li>B</li></ol></li><li>Two</li></ul>";
+        assert_eq!(html_to_text(html), "- One\n  1. A\n  2. B\n- Two");
+    }
+
+    #[test]
+    fn links_append_their_href() {
+        assert_eq!(
+            html_to_text(r#"See <a href="https://example.com/docs">the docs</a>."#),
+            "See the docs (https://example.com/docs)."
+        );
+        // A link whose text is already its target isn't repeated.
+        assert_eq!(html_to_text(r#"<a href="mailto:a@example.com">a@example.com</a>"#), "a@example.com");
+    }
+
+    #[test]
+    fn entities_are_decoded() {
+        assert_eq!(html_to_text("Tom &amp; Jerry&nbsp;&#x1F600; &#169; &lt;tag&gt;"), "Tom & Jerry 😀 © <tag>");
+        assert_eq!(html_to_text("AT&T &unknown;"), "AT&T &unknown;");
+    }
+
+    #[test]
+    fn head_style_and_script_are_skipped() {
+        let html = "<html><head><title>Title</title><style>p { color: red }</style></head>\
+                    <body><script>alert(1)</script><p>Visible</p></body></html>";
+        assert_eq!(html_to_text(html), "Visible");
+    }
+
+    #[test]
+    fn bare_less_than_is_text() {
+        assert_eq!(html_to_text("if a < b and c > d then"), "if a < b and c > d then");
+        assert_eq!(html_to_text("<p>1 <2</p>"), "1 <2");
+        assert_eq!(html_to_text("x <= y"), "x <= y");
+    }
+
+    #[test]
+    fn pre_keeps_whitespace() {
+        assert_eq!(html_to_text("<pre>  a\n    b</pre>"), "  a\n    b");
+        assert_eq!(html_to_text("<p>Code:</p><pre>  indented</pre>"), "Code:\n\n  indented");
+    }
+}
@@ -1,10 +1,24 @@
+mod audit;
+mod cli;
+mod download;
 mod gmail_client;
+mod health;
+mod html_text;
+mod output;
+mod template;
 
-use anyhow::Result;
-use gmail_client::GmailClient;
+use anyhow::{Context, Result};
+use clap::Parser;
+use audit::{AuditLog, ToolOutcome};
+use cli::{Cli, Command};
+use gmail_client::{BodyEncoding, Composer, GmailClient, OutgoingEmail, RecipientRejected, ReplyLoop, SendLimitExceeded, UrlAttachment};
+use output::Output;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use tokio::io::{AsyncBufReadExt, BufReader};
+use std::net::SocketAddr;
+use std::sync::OnceLock;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, BufReader};
+use tokio::task::JoinHandle;
 
 /// Represents a JSON-RPC 2.0 Request.
 #[derive(Serialize, Deserialize, Debug)]
@@ -34,6 +48,14 @@ struct JsonRpcError {
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    if let Some(Command::Capabilities) = cli.command {
+        // Same serialization as the `tools/list` result sent over the protocol.
+        println!("{}", serde_json::to_string(&tools_list(&cli))?);
+        return Ok(());
+    }
+
     eprintln!("Starting Gmail MCP Server (SMTP Version)...");
     
     // Check for credentials availability
@@ -41,11 +63,67 @@ async fn main() -> Result<()> {
         eprintln!("Warning: GMAIL_USER or GMAIL_APP_PASSWORD not set. Email sending will fail.");
     }
 
+    if cli.prewarm {
+        match client_from_env(&cli) {
+            Ok(client) => match client.test_connection().await {
+                Ok(()) => eprintln!("Pre-warmed SMTP connection to smtp.gmail.com."),
+                Err(e) => eprintln!("Warning: --prewarm failed: {}", e),
+            },
+            Err(e) => eprintln!("Warning: skipping --prewarm: {}", e.message),
+        }
+    }
+
+    if let Some(port) = cli.health_port {
+        let address = SocketAddr::new(cli.health_bind, port);
+        let listener = tokio::net::TcpListener::bind(address)
+            .await
+            .with_context(|| format!("Failed to listen for health checks on {}", address))?;
+        eprintln!("Serving /healthz and /readyz on {}.", address);
+        tokio::spawn(health::serve(listener, cli.clone()));
+    }
+
+    let audit = cli
+        .audit_log
+        .clone()
+        .map(|path| AuditLog::spawn(path, cli.audit_log_max_bytes));
+    let audit_log = audit.as_ref().map(|(log, _)| log);
+
+    if let Some(request) = &cli.once {
+        let result = run_once(request, &cli, audit_log).await;
+        finish_audit(audit).await;
+        return result;
+    }
+
     let stdin = tokio::io::stdin();
-    let mut reader = BufReader::new(stdin).lines();
+    let mut reader = BufReader::new(stdin);
+    let mut lifecycle = Lifecycle::Uninitialized;
+    let (out, writer) = Output::spawn();
 
     // Read lines from stdin (JSON-RPC messages are line-delimited in this implementation)
-    while let Ok(Some(line)) = reader.next_line().await {
+    loop {
+        let line = match read_line(&mut reader, cli.max_body_bytes).await {
+            Ok(Line::Message(line)) => line,
+            Ok(Line::TooLarge) => {
+                eprintln!("Rejected request larger than {} bytes", cli.max_body_bytes);
+                write_response(&out, Value::Null, Err(JsonRpcError {
+                    code: -32600,
+                    message: format!("Request exceeds the maximum size of {} bytes", cli.max_body_bytes),
+                    data: None,
+                }));
+                continue;
+            }
+            Ok(Line::Eof) => {
+                // Clean EOF: the client closed stdin and is done with us.
+                eprintln!("stdin closed by client (EOF), shutting down.");
+                break;
+            }
+            Err(e) => {
+                finish_output(out, writer).await.ok();
+                finish_audit(audit).await;
+                return Err(e).context("Failed to read from stdin");
+            }
+        };
+
         if line.trim().is_empty() {
             continue;
         }
@@ -58,23 +136,244 @@ async fn main() -> Result<()> {
             }
         };
 
-        handle_request(req).await;
+        handle_request(req, &cli, &mut lifecycle, &out, audit_log).await;
+    }
+
+    // Make sure every response and audit entry written so far is flushed before exiting.
+    finish_audit(audit).await;
+    finish_output(out, writer).await
+}
+
+/// Waits until every entry queued on the audit log has been written.
+async fn finish_audit(audit: Option<(AuditLog, JoinHandle<()>)>) {
+    if let Some((log, writer)) = audit {
+        drop(log);
+        if let Err(e) = writer.await {
+            eprintln!("Warning: audit log writer failed: {}", e);
+        }
+    }
+}
+
+/// Waits until every message queued on `out` has been written to stdout.
+async fn finish_output(out: Output, writer: JoinHandle<std::io::Result<()>>) -> Result<()> {
+    drop(out);
+    writer
+        .await
+        .context("stdout writer task failed")?
+        .context("Failed to write to stdout")
+}
+
+/// Handles the single request given with `--once`, as if the client had already
+/// completed the MCP handshake, and writes its response to stdout.
+///
+/// The response is printed even for a request without an `id`, and an error
+/// response makes the process exit unsuccessfully.
+async fn run_once(request: &str, cli: &Cli, audit: Option<&AuditLog>) -> Result<()> {
+    let req: JsonRpcRequest = serde_json::from_str(request).context("Invalid --once request")?;
+    let id = req.id.clone().unwrap_or(Value::Null);
+
+    let (out, writer) = Output::spawn();
+    let response = dispatch(req, cli, &mut Lifecycle::Ready, audit).await;
+    let code = response.as_ref().err().map(|e| e.code);
+    write_response(&out, id, response);
+    finish_output(out, writer).await?;
+
+    match code {
+        Some(code) => anyhow::bail!("Request failed with JSON-RPC error {}", code),
+        None => Ok(()),
+    }
+}
+
+/// One line-delimited message read from stdin.
+#[derive(Debug, PartialEq)]
+enum Line {
+    Message(String),
+    /// The line was longer than the configured limit and has been discarded.
+    TooLarge,
+    Eof,
+}
+
+/// Reads the next line from `reader`, buffering at most `max_bytes` of it.
+///
+/// An oversized line is consumed up to its newline without being stored, so a
+/// single huge request can't grow memory without bound.
+async fn read_line<R: AsyncBufRead + Unpin>(reader: &mut R, max_bytes: usize) -> std::io::Result<Line> {
+    let mut buf = Vec::new();
+    let mut too_large = false;
+    let mut read_any = false;
+
+    loop {
+        let available = reader.fill_buf().await?;
+        if available.is_empty() {
+            if !read_any {
+                return Ok(Line::Eof);
+            }
+            break;
+        }
+        read_any = true;
+
+        let (chunk, consumed, done) = match available.iter().position(|&b| b == b'\n') {
+            Some(i) => (&available[..i], i + 1, true),
+            None => (available, available.len(), false),
+        };
+        if !too_large {
+            if buf.len() + chunk.len() > max_bytes {
+                too_large = true;
+                buf = Vec::new();
+            } else {
+                buf.extend_from_slice(chunk);
+            }
+        }
+        reader.consume(consumed);
+
+        if done {
+            break;
+        }
+    }
+
+    if too_large {
+        return Ok(Line::TooLarge);
+    }
+    Ok(Line::Message(String::from_utf8_lossy(&buf).into_owned()))
+}
+
+/// Every tool this server implements, whether or not it is enabled.
+const TOOL_NAMES: &[&str] = &["send_email", "send_templated", "preview_email"];
+
+/// Returns the `tools/list` result describing every tool this server exposes.
+fn tools_list(cli: &Cli) -> Value {
+    let mut tools = json!({
+        "tools": [
+            {
+                "name": "send_email",
+                "description": "Send an email with an optional attachment via Gmail",
+                "inputSchema": email_input_schema()
+            },
+            {
+                "name": "send_templated",
+                "description": "Send an email whose subject and body are rendered from a template with {{name}} placeholders. Values come from 'variables' and are HTML-escaped in HTML bodies; a placeholder without a matching variable is an error",
+                "inputSchema": templated_input_schema()
+            },
+            {
+                "name": "preview_email",
+                "description": "Build an email without sending it and return a summary for review: recipients, subject, body excerpt, attachments and total size. Takes the same arguments as send_email. Needs only GMAIL_USER, and lists URL attachments without downloading them",
+                "inputSchema": email_input_schema()
+            }
+        ]
+    });
+
+    tools["tools"]
+        .as_array_mut()
+        .unwrap()
+        .retain(|tool| tool["name"].as_str().is_some_and(|name| cli.tool_enabled(name)));
+    if cli.deny_attachments {
+        // Don't advertise arguments every call would reject.
+        for tool in tools["tools"].as_array_mut().unwrap() {
+            let properties = tool["inputSchema"]["properties"].as_object_mut().unwrap();
+            for argument in ATTACHMENT_ARGUMENTS {
+                properties.remove(*argument);
+            }
+        }
     }
+    tools
+}
+
+/// Tool arguments that attach files, refused under `--deny-attachments`.
+const ATTACHMENT_ARGUMENTS: &[&str] = &["attachment_path", "attachment_content_type", "attachments_url"];
+
+/// The input schema shared by `send_email` and `preview_email`.
+fn email_input_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "to": { "type": "string", "description": "Recipient email address" },
+            "subject": { "type": "string", "description": "Email subject" },
+            "body": { "type": "string", "description": "Plain text email body content" },
+            "body_html": { "type": "string", "description": "HTML email body (optional). A plain-text alternative is generated from it when 'body' is omitted" },
+            "body_encoding": {
+                "type": "string",
+                "enum": ["7bit", "8bit", "quoted-printable", "base64"],
+                "description": "Content-Transfer-Encoding for the body (optional). Defaults to 7bit for ASCII text and quoted-printable otherwise; 7bit and 8bit are rejected if the body doesn't fit them"
+            },
+            "charset": { "type": "string", "description": "Character set for the body, e.g. 'iso-8859-1' or 'shift_jis' (optional, default UTF-8). The body is transcoded and rejected if it contains characters the charset can't represent" },
+            "content_language": { "type": "string", "description": "Language of the body as a comma-separated list of language tags, e.g. 'de' or 'en-US, fr' (optional). Sent as the Content-Language header" },
+            "attachment_path": { "type": "string", "description": "Absolute path to an attachment file (optional)" },
+            "attachment_content_type": { "type": "string", "description": "MIME type of the attachment, e.g. 'image/heic' (optional). Overrides the type guessed from the file extension" },
+            "attachments_url": {
+                "type": "array",
+                "description": "Remote files to download over https:// and attach (optional). The type comes from the response's Content-Type or the URL",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "url": { "type": "string", "description": "https:// URL of the file" },
+                        "filename": { "type": "string", "description": "Attachment filename (optional). Defaults to the last part of the URL path" }
+                    },
+                    "required": ["url"]
+                }
+            },
+            "reject_duplicate_filenames": { "type": "boolean", "description": "Fail if two attachments have the same filename (optional, default false). By default later ones are renamed, e.g. 'report (2).pdf'" },
+            "bulk": { "type": "boolean", "description": "Mark the email as automated bulk mail with 'Precedence: bulk' and 'Auto-Submitted: auto-generated' so vacation auto-responders don't reply (optional, default false)" },
+            "list_unsubscribe": { "type": "string", "description": "Unsubscribe link(s) for the List-Unsubscribe header: comma-separated mailto: or https:// URIs (optional)" },
+            "reply_to": { "type": "string", "description": "Address replies should go to instead of the sender, e.g. 'Support <support@example.com>'; comma-separate several (optional)" },
+            "copy_self": { "type": "boolean", "description": "Bcc your own address so a copy arrives in your inbox, not just Sent (optional, default false)" },
+            "request_read_receipt": { "type": "boolean", "description": "Ask for a read receipt sent back to your address via Disposition-Notification-To and Return-Receipt-To (optional, default false). Best-effort: many mail clients ignore or prompt before honoring it" },
+            "in_reply_to": { "type": "string", "description": "Message-ID of the message being replied to, e.g. '<abc@mail.gmail.com>' (optional). Use with a matching 'Re:' subject so Gmail keeps the reply in the same conversation" },
+            "references": { "type": "string", "description": "Space-separated Message-IDs from the original's References header plus its Message-ID (optional). Defaults to 'in_reply_to'" },
+            "allow_self_reply": { "type": "boolean", "description": "Send even if 'in_reply_to' is a message this server sent recently (optional, default false). Without it such replies are refused to stop automated reply loops" }
+        },
+        "required": ["to", "subject"]
+    })
+}
+
+/// The input schema for `send_templated`: the email fields with templates in place of bodies.
+fn templated_input_schema() -> Value {
+    let mut schema = email_input_schema();
+    let properties = schema["properties"].as_object_mut().unwrap();
+    properties.remove("body");
+    properties.remove("body_html");
+    properties.insert("subject".to_string(), json!({ "type": "string", "description": "Email subject; may contain {{name}} placeholders" }));
+    properties.insert("template".to_string(), json!({ "type": "string", "description": "Plain text body template with {{name}} placeholders" }));
+    properties.insert("template_html".to_string(), json!({ "type": "string", "description": "HTML body template with {{name}} placeholders; values are HTML-escaped" }));
+    properties.insert("template_name".to_string(), json!({ "type": "string", "description": "Name of a template in the server's template directory (<name>.txt and/or <name>.html), instead of 'template'/'template_html'" }));
+    properties.insert("variables".to_string(), json!({ "type": "object", "description": "Values for the placeholders, e.g. {\"name\": \"Sarah\"}" }));
+    schema
+}
 
-    Ok(())
+/// Where the client is in the MCP initialization handshake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Lifecycle {
+    /// No `initialize` request yet.
+    Uninitialized,
+    /// `initialize` answered; waiting for `notifications/initialized`.
+    Initializing,
+    /// Handshake complete; tool calls are accepted.
+    Ready,
 }
 
 /// Handles a single JSON-RPC request and writes the response to stdout.
-async fn handle_request(req: JsonRpcRequest) {
+async fn handle_request(req: JsonRpcRequest, cli: &Cli, lifecycle: &mut Lifecycle, out: &Output, audit: Option<&AuditLog>) {
     let id = req.id.clone();
-    let response = match req.method.as_str() {
+    let response = dispatch(req, cli, lifecycle, audit).await;
+
+    // If request had an ID, send a response. If it was a notification (no ID), do nothing.
+    if let Some(id_val) = id {
+        write_response(out, id_val, response);
+    }
+}
+
+/// Runs a single JSON-RPC request and returns its result.
+async fn dispatch(req: JsonRpcRequest, cli: &Cli, lifecycle: &mut Lifecycle, audit: Option<&AuditLog>) -> Result<Value, JsonRpcError> {
+    match req.method.as_str() {
         "initialize" => {
+            if *lifecycle == Lifecycle::Uninitialized {
+                *lifecycle = Lifecycle::Initializing;
+            }
             // MCP Handshake: Return server capabilities
             Ok(json!({
                 "protocolVersion": "0.1.0",
                 "serverInfo": {
                     "name": "gmail-mcp-server",
-                    "version": "0.1.0"
+                    "version": env!("CARGO_PKG_VERSION")
                 },
                 "capabilities": {
                     "tools": {}
@@ -83,32 +382,43 @@ async fn handle_request(req: JsonRpcRequest) {
         }
         "notifications/initialized" => {
             // Client confirming initialization
+            match *lifecycle {
+                Lifecycle::Uninitialized => eprintln!("Ignoring notifications/initialized received before initialize"),
+                Lifecycle::Initializing => *lifecycle = Lifecycle::Ready,
+                Lifecycle::Ready => {}
+            }
             Ok(json!("OK"))
         }
         "tools/list" => {
             // List available tools
-            Ok(json!({
-                "tools": [
-                    {
-                        "name": "send_email",
-                        "description": "Send an email with an optional attachment via Gmail",
-                        "inputSchema": {
-                            "type": "object",
-                            "properties": {
-                                "to": { "type": "string", "description": "Recipient email address" },
-                                "subject": { "type": "string", "description": "Email subject" },
-                                "body": { "type": "string", "description": "Email body content" },
-                                "attachment_path": { "type": "string", "description": "Absolute path to an attachment file (optional)" }
-                            },
-                            "required": ["to", "subject", "body"]
-                        }
-                    }
-                ]
-            }))
+            Ok(tools_list(cli))
+        }
+        "echo" if cli.debug => {
+            // Debugging aid: returns the params exactly as they were parsed.
+            Ok(req.params.unwrap_or(Value::Null))
         }
         "tools/call" => {
             // Execute a tool
-            handle_tool_call(req.params).await
+            let outcome = match *lifecycle {
+                Lifecycle::Ready => handle_tool_call(req.params.as_ref(), cli).await,
+                _ => Err(JsonRpcError {
+                    code: -32002,
+                    message: match *lifecycle {
+                        Lifecycle::Uninitialized => "Server not initialized: send 'initialize' and then 'notifications/initialized' before calling tools",
+                        _ => "Server not initialized: send 'notifications/initialized' before calling tools",
+                    }
+                    .to_string(),
+                    data: None,
+                }),
+            };
+            // Record every attempt, including ones rejected before the tool ran.
+            if let Some(audit) = audit {
+                let params = req.params.as_ref();
+                let tool = params.and_then(|p| p.get("name")).and_then(|n| n.as_str());
+                let args = params.and_then(|p| p.get("arguments")).unwrap_or(&Value::Null);
+                audit.record(tool, args, &outcome);
+            }
+            outcome.map(|outcome| outcome.result)
         }
         _ => {
             // Method not found
@@ -118,32 +428,31 @@ async fn handle_request(req: JsonRpcRequest) {
                 data: None,
             })
         }
+    }
+}
+
+/// Queues a JSON-RPC response for request `id` on `out`.
+fn write_response(out: &Output, id: Value, response: Result<Value, JsonRpcError>) {
+    let resp = match response {
+        Ok(res) => JsonRpcResponse {
+            jsonrpc: "2.0".to_string(),
+            id: Some(id),
+            result: Some(res),
+            error: None,
+        },
+        Err(e) => JsonRpcResponse {
+            jsonrpc: "2.0".to_string(),
+            id: Some(id),
+            result: None,
+            error: Some(e),
+        },
     };
 
-    // If request had an ID, send a response. If it was a notification (no ID), do nothing.
-    if let Some(id_val) = id {
-        let resp = match response {
-            Ok(res) => JsonRpcResponse {
-                jsonrpc: "2.0".to_string(),
-                id: Some(id_val),
-                result: Some(res),
-                error: None,
-            },
-            Err(e) => JsonRpcResponse {
-                jsonrpc: "2.0".to_string(),
-                id: Some(id_val),
-                result: None,
-                error: Some(e),
-            },
-        };
-        
-        let out = serde_json::to_string(&resp).unwrap();
-        println!("{}", out);
-    }
+    out.send(serde_json::to_string(&resp).unwrap());
 }
 
 /// Dispatches tool calls to specific implementations.
-async fn handle_tool_call(params: Option<Value>) -> Result<Value, JsonRpcError> {
+async fn handle_tool_call(params: Option<&Value>, cli: &Cli) -> Result<ToolOutcome, JsonRpcError> {
     let params = params.ok_or(JsonRpcError {
         code: -32602,
         message: "Missing params".to_string(),
@@ -156,53 +465,419 @@ async fn handle_tool_call(params: Option<Value>) -> Result<Value, JsonRpcError>
         data: None,
     })?;
 
-    if name == "send_email" {
-        let args = params.get("arguments").ok_or(JsonRpcError{
-            code: -32602,
-            message: "Missing arguments".to_string(),
-            data: None,
-        })?;
+    let args = params.get("arguments").ok_or(JsonRpcError{
+        code: -32602,
+        message: "Missing arguments".to_string(),
+        data: None,
+    })?;
 
-        let to = args.get("to").and_then(|s| s.as_str()).ok_or(JsonRpcError{
-             code: -32602, message: "Missing 'to'".to_string(), data: None
-        })?;
-        let subject = args.get("subject").and_then(|s| s.as_str()).unwrap_or("(No Subject)");
-        let body = args.get("body").and_then(|s| s.as_str()).unwrap_or("");
-        let attachment_path = args.get("attachment_path").and_then(|s| s.as_str());
-
-        // Get credentials from env
-        let username = std::env::var("GMAIL_USER").map_err(|_| JsonRpcError {
-            code: -32000,
-            message: "GMAIL_USER env var not set".to_string(),
-            data: None,
-        })?;
-        let password = std::env::var("GMAIL_APP_PASSWORD").map_err(|_| JsonRpcError {
-            code: -32000,
-            message: "GMAIL_APP_PASSWORD env var not set".to_string(),
+    call_tool(name, args, cli).await
+}
+
+/// Runs tool `name` with `args`.
+async fn call_tool(name: &str, args: &Value, cli: &Cli) -> Result<ToolOutcome, JsonRpcError> {
+    if TOOL_NAMES.contains(&name) && !cli.tool_enabled(name) {
+        return Err(JsonRpcError {
+            code: -32601,
+            message: format!("Tool '{}' is disabled on this server", name),
             data: None,
-        })?;
+        });
+    }
+    if cli.deny_attachments {
+        let given = ATTACHMENT_ARGUMENTS.iter().find(|a| args.get(**a).is_some_and(|v| !v.is_null()));
+        if let Some(argument) = given {
+            return Err(JsonRpcError {
+                code: -32602,
+                message: format!("Attachments are disabled on this server (--deny-attachments); remove '{}'", argument),
+                data: None,
+            });
+        }
+    }
 
-        let client = GmailClient::new(username, password);
+    match name {
+        "send_email" => {
+            let email = parse_email_args(args)?;
+            send_email(&email, cli).await
+        }
+        "send_templated" => {
+            let email = parse_templated_args(args, cli).await?;
+            send_email(&email, cli).await
+        }
+        "preview_email" => {
+            let email = parse_email_args(args)?;
+            let composer = composer_from_env(cli)?;
 
-        let result = client.send_email(to, subject, body, attachment_path).await.map_err(|e| JsonRpcError {
-            code: -32000,
-            message: format!("Failed to send email: {}", e),
-            data: None,
-        })?;
+            let preview = composer.preview_email(&email).await.map_err(|e| JsonRpcError {
+                code: -32000,
+                message: format!("Failed to build email: {}", e),
+                data: None,
+            })?;
+
+            Ok(ToolOutcome::new(json!({
+                "content": [
+                    {
+                        "type": "text",
+                        "text": serde_json::to_string_pretty(&preview).unwrap()
+                    }
+                ]
+            })))
+        }
+        _ => Err(unknown_tool(name, cli)),
+    }
+}
+
+/// The error for a call to a tool that doesn't exist, listing the valid tools
+/// and suggesting the closest name when it looks like a typo.
+fn unknown_tool(name: &str, cli: &Cli) -> JsonRpcError {
+    let tools = tools_list(cli);
+    let valid: Vec<&str> = tools["tools"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|tool| tool["name"].as_str())
+        .collect();
+
+    let suggestion = valid
+        .iter()
+        .map(|candidate| (edit_distance(name, candidate), *candidate))
+        .filter(|(distance, candidate)| *distance <= (candidate.len() / 3).max(2))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate);
+
+    let message = match suggestion {
+        Some(candidate) => format!("Unknown tool: {} (did you mean {}?)", name, candidate),
+        None => format!("Unknown tool: {}", name),
+    };
+    JsonRpcError {
+        code: -32601,
+        message,
+        data: Some(json!({
+            "valid_tools": valid,
+            "suggestion": suggestion,
+        })),
+    }
+}
+
+/// Levenshtein distance between `a` and `b`, counted in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Sends `email` and reports the outcome as a tool result.
+async fn send_email(email: &OutgoingEmail, cli: &Cli) -> Result<ToolOutcome, JsonRpcError> {
+    let client = client_from_env(cli)?;
 
-        Ok(json!({
+    let sent = client.send_email(email).await.map_err(|e| JsonRpcError {
+        code: -32000,
+        message: format!("Failed to send email: {}", e),
+        data: send_error_data(&e),
+    })?;
+
+    Ok(ToolOutcome {
+        result: json!({
             "content": [
                 {
                     "type": "text",
-                    "text": format!("Email sent successfully. Result: {}", result)
+                    "text": format!(
+                        "Email sent successfully to {}. Message-ID: {}. Result: {}",
+                        sent.recipients.join(", "),
+                        sent.message_id,
+                        sent.response
+                    )
                 }
             ]
-        }))
-    } else {
-        Err(JsonRpcError {
-            code: -32601,
-            message: format!("Unknown tool: {}", name),
+        }),
+        message_id: Some(sent.message_id),
+        recipients: sent.recipients,
+    })
+}
+
+/// Structured `data` for send failures a caller can act on.
+fn send_error_data(error: &anyhow::Error) -> Option<Value> {
+    if let Some(rejected) = error.downcast_ref::<RecipientRejected>() {
+        return Some(json!({
+            "reason": "invalid_recipient",
+            "smtp_code": rejected.smtp_code,
+            "detail": rejected.detail,
+            "recipients": rejected.recipients,
+        }));
+    }
+    if let Some(limit) = error.downcast_ref::<SendLimitExceeded>() {
+        return Some(json!({
+            "reason": "daily_send_limit",
+            "retryable": false,
+            "detail": limit.detail,
+            "resets_at": limit.resets_at.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        }));
+    }
+    if let Some(reply_loop) = error.downcast_ref::<ReplyLoop>() {
+        return Some(json!({
+            "reason": "reply_loop",
+            "in_reply_to": reply_loop.in_reply_to,
+        }));
+    }
+    None
+}
+
+/// Builds the email for a `send_templated` call by rendering its subject and body templates.
+async fn parse_templated_args(args: &Value, cli: &Cli) -> Result<OutgoingEmail, JsonRpcError> {
+    let invalid = |message: String| JsonRpcError {
+        code: -32602,
+        message,
+        data: None,
+    };
+    let str_arg = |key: &str| args.get(key).and_then(|s| s.as_str()).map(str::to_string);
+
+    let mut email = parse_email_args(args)?;
+    let variables = match args.get("variables") {
+        None | Some(Value::Null) => serde_json::Map::new(),
+        Some(Value::Object(variables)) => variables.clone(),
+        Some(_) => return Err(invalid("'variables' must be an object".to_string())),
+    };
+
+    let (text, html) = match str_arg("template_name") {
+        Some(name) => load_named_template(&name, cli).await.map_err(|e| invalid(e.to_string()))?,
+        None => (str_arg("template"), str_arg("template_html")),
+    };
+    if text.is_none() && html.is_none() {
+        return Err(invalid("Provide 'template', 'template_html' or 'template_name'".to_string()));
+    }
+
+    let render = |template: &str, escape_html: bool| {
+        template::render(template, &variables, escape_html).map_err(|e| invalid(e.to_string()))
+    };
+    email.subject = render(&email.subject, false)?;
+    email.body = text.map(|t| render(&t, false)).transpose()?;
+    email.body_html = html.map(|t| render(&t, true)).transpose()?;
+
+    Ok(email)
+}
+
+/// Loads `<name>.txt` and/or `<name>.html` from the `--template-dir` directory.
+async fn load_named_template(name: &str, cli: &Cli) -> Result<(Option<String>, Option<String>)> {
+    let dir = cli
+        .template_dir
+        .as_ref()
+        .context("'template_name' requires the server to be started with --template-dir")?;
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        anyhow::bail!("Invalid template name '{}': use only letters, digits, '-' and '_'", name);
+    }
+
+    let read = |extension: &str| {
+        let path = dir.join(format!("{}.{}", name, extension));
+        async move {
+            match tokio::fs::read_to_string(&path).await {
+                Ok(content) => Ok(Some(content)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e).with_context(|| format!("Failed to read template {}", path.display())),
+            }
+        }
+    };
+    let text = read("txt").await?;
+    let html = read("html").await?;
+    if text.is_none() && html.is_none() {
+        anyhow::bail!("No template named '{}' ({}.txt or {}.html) in {}", name, name, name, dir.display());
+    }
+
+    Ok((text, html))
+}
+
+/// Extracts the email fields shared by `send_email` and `preview_email` from tool arguments.
+fn parse_email_args(args: &Value) -> Result<OutgoingEmail, JsonRpcError> {
+    let str_arg = |key: &str| args.get(key).and_then(|s| s.as_str()).map(str::to_string);
+
+    let to = str_arg("to").ok_or(JsonRpcError{
+         code: -32602, message: "Missing 'to'".to_string(), data: None
+    })?;
+
+    let body_encoding = str_arg("body_encoding")
+        .map(|e| e.parse::<BodyEncoding>())
+        .transpose()
+        .map_err(|e| JsonRpcError {
+            code: -32602,
+            message: e.to_string(),
             data: None,
-        })
+        })?;
+
+    let attachment_urls = match args.get("attachments_url") {
+        None | Some(Value::Null) => Vec::new(),
+        Some(Value::Array(items)) => items
+            .iter()
+            .map(|item| {
+                let url = item.get("url").and_then(|u| u.as_str()).ok_or(JsonRpcError {
+                    code: -32602,
+                    message: "Each 'attachments_url' entry needs a 'url' string".to_string(),
+                    data: None,
+                })?;
+                Ok(UrlAttachment {
+                    url: url.to_string(),
+                    filename: item.get("filename").and_then(|f| f.as_str()).map(str::to_string),
+                })
+            })
+            .collect::<Result<_, JsonRpcError>>()?,
+        Some(_) => {
+            return Err(JsonRpcError {
+                code: -32602,
+                message: "'attachments_url' must be an array".to_string(),
+                data: None,
+            })
+        }
+    };
+
+    Ok(OutgoingEmail {
+        to,
+        subject: str_arg("subject").unwrap_or_else(|| "(No Subject)".to_string()),
+        body: str_arg("body"),
+        body_html: str_arg("body_html"),
+        attachment_path: str_arg("attachment_path"),
+        attachment_content_type: str_arg("attachment_content_type"),
+        attachment_urls,
+        body_encoding,
+        charset: str_arg("charset"),
+        content_language: str_arg("content_language"),
+        in_reply_to: str_arg("in_reply_to"),
+        references: str_arg("references"),
+        allow_self_reply: args.get("allow_self_reply").and_then(|b| b.as_bool()).unwrap_or(false),
+        reply_to: str_arg("reply_to"),
+        copy_self: args.get("copy_self").and_then(|b| b.as_bool()).unwrap_or(false),
+        request_read_receipt: args.get("request_read_receipt").and_then(|b| b.as_bool()).unwrap_or(false),
+        reject_duplicate_filenames: args.get("reject_duplicate_filenames").and_then(|b| b.as_bool()).unwrap_or(false),
+        bulk: args.get("bulk").and_then(|b| b.as_bool()).unwrap_or(false),
+        list_unsubscribe: str_arg("list_unsubscribe"),
+    })
+}
+
+/// A composer for previews, which need the sender's address but not its password.
+fn composer_from_env(cli: &Cli) -> Result<Composer, JsonRpcError> {
+    let username = std::env::var("GMAIL_USER").map_err(|_| JsonRpcError {
+        code: -32000,
+        message: "GMAIL_USER env var not set".to_string(),
+        data: None,
+    })?;
+    Ok(Composer::new(username, cli.client_options()))
+}
+
+/// The client shared by every sending tool call, so pooled SMTP connections are reused.
+static CLIENT: OnceLock<GmailClient> = OnceLock::new();
+
+/// Returns the shared [`GmailClient`], building it from the `GMAIL_USER` and
+/// `GMAIL_APP_PASSWORD` env vars on first use.
+fn client_from_env(cli: &Cli) -> Result<&'static GmailClient, JsonRpcError> {
+    if let Some(client) = CLIENT.get() {
+        return Ok(client);
+    }
+
+    let username = std::env::var("GMAIL_USER").map_err(|_| JsonRpcError {
+        code: -32000,
+        message: "GMAIL_USER env var not set".to_string(),
+        data: None,
+    })?;
+    let password = std::env::var("GMAIL_APP_PASSWORD").map_err(|_| JsonRpcError {
+        code: -32000,
+        message: "GMAIL_APP_PASSWORD env var not set".to_string(),
+        data: None,
+    })?;
+
+    let client = GmailClient::new(username, password, cli.client_options()).map_err(|e| JsonRpcError {
+        code: -32000,
+        message: e.to_string(),
+        data: None,
+    })?;
+    Ok(CLIENT.get_or_init(|| client))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads lines until EOF through a 4-byte buffer, so lines span several fills.
+    async fn read_all(input: &[u8], max_bytes: usize) -> Vec<Line> {
+        let mut reader = BufReader::with_capacity(4, input);
+        let mut lines = Vec::new();
+        loop {
+            match read_line(&mut reader, max_bytes).await.unwrap() {
+                Line::Eof => return lines,
+                line => lines.push(line),
+            }
+        }
+    }
+
+    fn message(s: &str) -> Line {
+        Line::Message(s.to_string())
+    }
+
+    #[tokio::test]
+    async fn line_at_the_limit_is_accepted() {
+        assert_eq!(read_all(b"0123456789\n", 10).await, [message("0123456789")]);
+    }
+
+    #[tokio::test]
+    async fn line_one_byte_over_the_limit_is_rejected() {
+        assert_eq!(read_all(b"0123456789A\n", 10).await, [Line::TooLarge]);
+    }
+
+    #[tokio::test]
+    async fn line_after_an_oversized_one_still_parses() {
+        let input = b"0123456789ABCDEF\n{\"id\":1}\n";
+        assert_eq!(read_all(input, 10).await, [Line::TooLarge, message("{\"id\":1}")]);
+    }
+
+    #[tokio::test]
+    async fn final_line_without_newline_is_returned() {
+        assert_eq!(read_all(b"first\nlast", 10).await, [message("first"), message("last")]);
+    }
+
+    #[tokio::test]
+    async fn empty_input_is_eof() {
+        assert_eq!(read_all(b"", 10).await, []);
+    }
+
+    fn cli(args: &[&str]) -> Cli {
+        Cli::parse_from(std::iter::once("gmail-mcp-server").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn edit_distance_counts_characters() {
+        assert_eq!(edit_distance("send_email", "send_email"), 0);
+        assert_eq!(edit_distance("sned_email", "send_email"), 2);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("café", "cafe"), 1);
+    }
+
+    #[test]
+    fn unknown_tool_suggests_a_close_match() {
+        let error = unknown_tool("sned_email", &cli(&[]));
+        assert_eq!(error.code, -32601);
+        assert_eq!(error.message, "Unknown tool: sned_email (did you mean send_email?)");
+        assert_eq!(error.data.unwrap()["suggestion"], "send_email");
+    }
+
+    #[test]
+    fn unknown_tool_suggests_nothing_for_unrelated_names() {
+        // 'send_email' allows a distance of 3; 'sendxxxxmail' is 4 away.
+        for name in ["list_labels", "sendxxxxmail"] {
+            let error = unknown_tool(name, &cli(&[]));
+            assert_eq!(error.message, format!("Unknown tool: {}", name));
+            assert_eq!(error.data.unwrap()["suggestion"], Value::Null);
+        }
+        assert_eq!(unknown_tool("sendxxxmail", &cli(&[])).data.unwrap()["suggestion"], "send_email");
+    }
+
+    #[test]
+    fn unknown_tool_lists_only_enabled_tools() {
+        let error = unknown_tool("preview_emial", &cli(&["--disabled-tools", "preview_email"]));
+        let data = error.data.unwrap();
+        assert_eq!(data["valid_tools"], json!(["send_email", "send_templated"]));
+        assert_eq!(data["suggestion"], Value::Null);
     }
 }
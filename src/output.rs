@@ -0,0 +1,40 @@
+use tokio::io::{AsyncWriteExt, Stdout};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// A handle for writing JSON-RPC messages to stdout.
+///
+/// All handles feed one writer task, which writes and flushes each message as a
+/// whole line, so messages sent from concurrent tasks can never interleave.
+#[derive(Clone)]
+pub struct Output {
+    sender: mpsc::UnboundedSender<String>,
+}
+
+impl Output {
+    /// Starts the writer task.
+    ///
+    /// The task ends once every `Output` has been dropped and the queue is
+    /// drained; awaiting the returned handle then guarantees everything sent
+    /// has reached stdout.
+    pub fn spawn() -> (Self, JoinHandle<std::io::Result<()>>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let writer = tokio::spawn(write_lines(receiver, tokio::io::stdout()));
+        (Self { sender }, writer)
+    }
+
+    /// Queues one serialized message. It must not contain a newline.
+    pub fn send(&self, message: String) {
+        // The writer only stops early on a stdout error, which its task returns.
+        let _ = self.sender.send(message);
+    }
+}
+
+async fn write_lines(mut receiver: mpsc::UnboundedReceiver<String>, mut stdout: Stdout) -> std::io::Result<()> {
+    while let Some(mut message) = receiver.recv().await {
+        message.push('\n');
+        stdout.write_all(message.as_bytes()).await?;
+        stdout.flush().await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,124 @@
+use anyhow::{bail, Result};
+use serde_json::{Map, Value};
+
+/// Renders `template`, replacing each `{{name}}` placeholder with the matching
+/// entry from `variables`.
+///
+/// Whitespace inside the braces is ignored, so `{{ name }}` also works. When
+/// `escape_html` is set, substituted values are HTML-escaped while the template
+/// text itself is left untouched. A placeholder with no matching variable is an
+/// error rather than being rendered literally.
+pub fn render(template: &str, variables: &Map<String, Value>, escape_html: bool) -> Result<String> {
+    let mut out = String::with_capacity(template.len());
+    let mut missing = Vec::new();
+    let mut rest = template;
+
+    while let Some(open) = rest.find("{{") {
+        out.push_str(&rest[..open]);
+        let after_open = &rest[open + 2..];
+        let Some(close) = after_open.find("}}") else {
+            bail!("Unclosed '{{{{' in template");
+        };
+
+        let name = after_open[..close].trim();
+        if name.is_empty() {
+            bail!("Empty placeholder '{{{{}}}}' in template");
+        }
+        match variables.get(name) {
+            Some(value) => {
+                let value = variable_text(name, value)?;
+                if escape_html {
+                    out.push_str(&escape(&value));
+                } else {
+                    out.push_str(&value);
+                }
+            }
+            None => {
+                if !missing.iter().any(|m| m == name) {
+                    missing.push(name.to_string());
+                }
+            }
+        }
+        rest = &after_open[close + 2..];
+    }
+    out.push_str(rest);
+
+    if !missing.is_empty() {
+        bail!("Template uses undeclared variables: {}", missing.join(", "));
+    }
+    Ok(out)
+}
+
+/// The text substituted for a variable. Only scalar values are allowed.
+fn variable_text(name: &str, value: &Value) -> Result<String> {
+    match value {
+        Value::String(s) => Ok(s.clone()),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::Bool(b) => Ok(b.to_string()),
+        Value::Null => Ok(String::new()),
+        Value::Array(_) | Value::Object(_) => {
+            bail!("Variable '{}' must be a string, number, boolean or null", name)
+        }
+    }
+}
+
+/// Escapes the characters that are significant in HTML text and attributes.
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn vars(value: Value) -> Map<String, Value> {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn substitutes_repeated_and_spaced_placeholders() {
+        let variables = vars(json!({ "name": "Ada", "count": 3, "vip": true }));
+        assert_eq!(
+            render("Hi {{name}}, {{ name }} has {{count}} items (vip: {{vip}})", &variables, false).unwrap(),
+            "Hi Ada, Ada has 3 items (vip: true)"
+        );
+    }
+
+    #[test]
+    fn escapes_values_but_not_the_template_in_html() {
+        let variables = vars(json!({ "name": "<b>Tom & \"Jerry\"</b>'s" }));
+        assert_eq!(
+            render("<p>{{name}}</p>", &variables, true).unwrap(),
+            "<p>&lt;b&gt;Tom &amp; &quot;Jerry&quot;&lt;/b&gt;&#39;s</p>"
+        );
+        assert_eq!(render("{{name}}", &variables, false).unwrap(), "<b>Tom & \"Jerry\"</b>'s");
+    }
+
+    #[test]
+    fn missing_variables_are_listed_once() {
+        let variables = vars(json!({ "name": "Ada" }));
+        let error = render("{{first}} {{name}} {{last}} {{first}}", &variables, false).unwrap_err();
+        assert_eq!(error.to_string(), "Template uses undeclared variables: first, last");
+    }
+
+    #[test]
+    fn malformed_placeholders_are_errors() {
+        let variables = vars(json!({ "name": "Ada" }));
+        assert_eq!(render("Hi {{name", &variables, false).unwrap_err().to_string(), "Unclosed '{{' in template");
+        assert_eq!(render("Hi {{ }}", &variables, false).unwrap_err().to_string(), "Empty placeholder '{{}}' in template");
+        let nested = vars(json!({ "list": [1, 2] }));
+        assert!(render("{{list}}", &nested, false).is_err());
+    }
+}